@@ -2,7 +2,11 @@ use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
 
 const KEYCHAIN_SERVICE: &str = "com.tunnel-cli.cloudflare";
 
@@ -11,13 +15,27 @@ const KEYCHAIN_SERVICE: &str = "com.tunnel-cli.cloudflare";
 #[derive(Parser)]
 #[command(name = "talpa", about = "Cloudflare Tunnel route manager")]
 struct Cli {
+    /// Credential backend to use (default: auto-detected per platform)
+    #[arg(long, global = true, value_enum)]
+    credential_backend: Option<CredentialBackend>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum CredentialBackend {
+    /// macOS Keychain, via the `security` CLI
+    Keychain,
+    /// Linux Secret Service (GNOME Keyring, KWallet, ...), via `secret-tool`
+    SecretService,
+    /// Environment variables and/or `~/.config/talpa/config.toml`
+    Config,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Initial setup — store credentials in macOS Keychain
+    /// Initial setup — store credentials in the active credential backend
     Setup,
     /// Dig a new tunnel route + CNAME record
     Dig {
@@ -25,6 +43,24 @@ enum Commands {
         hostname: String,
         /// Local service (e.g. http://localhost:8080)
         service: String,
+        /// Wait for the CNAME to resolve before declaring success
+        #[arg(long)]
+        wait: bool,
+        /// Max seconds to wait for propagation (implies --wait)
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Disable TLS certificate verification against the origin
+        #[arg(long)]
+        no_tls_verify: bool,
+        /// Override the Host header sent to the origin
+        #[arg(long)]
+        http_host_header: Option<String>,
+        /// Hostname to verify the origin's TLS certificate against
+        #[arg(long)]
+        origin_server_name: Option<String>,
+        /// Timeout in seconds for connecting to the origin
+        #[arg(long)]
+        connect_timeout: Option<u64>,
     },
     /// Plug (remove) a tunnel route + CNAME record
     Plug {
@@ -33,54 +69,285 @@ enum Commands {
     },
     /// List all active routes
     List,
+    /// Show the plan that `apply` would execute against a routes file
+    Diff {
+        /// Path to a TOML routes file
+        file: PathBuf,
+    },
+    /// Reconcile the tunnel config + DNS to match a routes file
+    Apply {
+        /// Path to a TOML routes file
+        file: PathBuf,
+        /// Remove hostnames that are live but absent from the routes file
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Reap DNS CNAMEs pointing at this tunnel that no longer have an ingress rule
+    Prune {
+        /// Any hostname/domain in the zone to prune (used to resolve the zone)
+        zone: String,
+        /// Only report orphaned records, don't delete anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Confirm deletion without an interactive prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Provision and manage Cloudflare Tunnels on this account
+    Tunnel {
+        #[command(subcommand)]
+        action: TunnelAction,
+    },
+    /// Manage WARP-routed private-network IP routes for this tunnel
+    RouteIp {
+        #[command(subcommand)]
+        action: RouteIpAction,
+    },
 }
 
-// ─── macOS Keychain ──────────────────────────────────────────
-
-fn keychain_set(account: &str, password: &str) -> Result<()> {
-    let _ = Command::new("security")
-        .args(["delete-generic-password", "-s", KEYCHAIN_SERVICE, "-a", account])
-        .output();
-
-    let output = Command::new("security")
-        .args([
-            "add-generic-password",
-            "-s", KEYCHAIN_SERVICE,
-            "-a", account,
-            "-w", password,
-            "-U",
-        ])
-        .output()
-        .context("Failed to run `security` command")?;
-
-    if !output.status.success() {
-        bail!(
-            "Keychain write failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+#[derive(Subcommand)]
+enum TunnelAction {
+    /// Create a new tunnel
+    Create {
+        /// Name for the new tunnel
+        name: String,
+    },
+    /// Delete a tunnel
+    Delete {
+        /// Tunnel ID to delete
+        id: String,
+    },
+    /// List tunnels on this account
+    Ls,
+}
+
+#[derive(Subcommand)]
+enum RouteIpAction {
+    /// Route a private-network CIDR through this tunnel
+    Add {
+        /// CIDR to route (e.g. 10.0.0.0/24)
+        cidr: String,
+    },
+    /// Remove a private-network CIDR route
+    Rm {
+        /// CIDR to remove
+        cidr: String,
+    },
+    /// List private-network IP routes on this account
+    Ls,
+}
+
+// ─── Credential Backends ─────────────────────────────────────
+
+trait CredentialStore {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+    fn delete(&self, key: &str) -> Result<()>;
+    fn describe(&self) -> String;
+}
+
+// Picks the natural backend for the current platform; overridden by --credential-backend.
+fn default_backend() -> Box<dyn CredentialStore> {
+    if cfg!(target_os = "macos") {
+        Box::new(KeychainStore)
+    } else if cfg!(target_os = "linux") {
+        Box::new(SecretServiceStore)
+    } else {
+        Box::new(ConfigStore::default())
     }
-    Ok(())
 }
 
-fn keychain_get(account: &str) -> Result<String> {
-    let output = Command::new("security")
-        .args([
-            "find-generic-password",
-            "-s", KEYCHAIN_SERVICE,
-            "-a", account,
-            "-w",
-        ])
-        .output()
-        .context("Failed to run `security` command")?;
-
-    if !output.status.success() {
-        bail!(
-            "Keychain read failed for '{}'. Run `tunnel setup` first.",
-            account
-        );
+fn resolve_backend(choice: Option<CredentialBackend>) -> Box<dyn CredentialStore> {
+    match choice {
+        Some(CredentialBackend::Keychain) => Box::new(KeychainStore),
+        Some(CredentialBackend::SecretService) => Box::new(SecretServiceStore),
+        Some(CredentialBackend::Config) => Box::new(ConfigStore::default()),
+        None => default_backend(),
     }
+}
+
+struct KeychainStore;
 
-    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+impl CredentialStore for KeychainStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", key, "-w"])
+            .output()
+            .context("Failed to run `security` command")?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8(output.stdout)?.trim().to_string()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let _ = self.delete(key);
+
+        let output = Command::new("security")
+            .args([
+                "add-generic-password",
+                "-s", KEYCHAIN_SERVICE,
+                "-a", key,
+                "-w", value,
+                "-U",
+            ])
+            .output()
+            .context("Failed to run `security` command")?;
+
+        if !output.status.success() {
+            bail!(
+                "Keychain write failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let _ = Command::new("security")
+            .args(["delete-generic-password", "-s", KEYCHAIN_SERVICE, "-a", key])
+            .output();
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("macOS Keychain (service: {KEYCHAIN_SERVICE})")
+    }
+}
+
+struct SecretServiceStore;
+
+impl CredentialStore for SecretServiceStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "service", KEYCHAIN_SERVICE, "account", key])
+            .output()
+            .context("Failed to run `secret-tool`. Is libsecret-tools installed?")?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8(output.stdout)?.trim().to_string()))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        use std::io::Write;
+        let mut child = Command::new("secret-tool")
+            .args([
+                "store",
+                "--label", &format!("talpa: {key}"),
+                "service", KEYCHAIN_SERVICE,
+                "account", key,
+            ])
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .context("Failed to run `secret-tool`. Is libsecret-tools installed?")?;
+
+        child
+            .stdin
+            .take()
+            .context("Failed to open secret-tool stdin")?
+            .write_all(value.as_bytes())?;
+
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("secret-tool store failed for '{key}'");
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let _ = Command::new("secret-tool")
+            .args(["clear", "service", KEYCHAIN_SERVICE, "account", key])
+            .output();
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("Linux Secret Service (service: {KEYCHAIN_SERVICE})")
+    }
+}
+
+// Env vars (TALPA_ACCOUNT_ID, TALPA_API_TOKEN, ...) or ~/.config/talpa/config.toml; env wins.
+struct ConfigStore {
+    path: PathBuf,
+}
+
+impl Default for ConfigStore {
+    fn default() -> Self {
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        Self {
+            path: PathBuf::from(home).join(".config/talpa/config.toml"),
+        }
+    }
+}
+
+impl ConfigStore {
+    fn env_var(key: &str) -> String {
+        format!("TALPA_{}", key.to_uppercase())
+    }
+
+    fn read_map(&self) -> Result<BTreeMap<String, String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(raw) => Ok(toml::from_str(&raw)
+                .with_context(|| format!("Failed to parse {}", self.path.display()))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+    }
+
+    fn write_map(&self, map: &BTreeMap<String, String>) -> Result<()> {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+            std::fs::set_permissions(parent, std::fs::Permissions::from_mode(0o700))?;
+        }
+
+        let raw = toml::to_string_pretty(map)?;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(raw.as_bytes())
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        // Belt-and-suspenders in case the file already existed with looser permissions.
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(())
+    }
+}
+
+impl CredentialStore for ConfigStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        if let Ok(value) = std::env::var(Self::env_var(key)) {
+            return Ok(Some(value));
+        }
+        Ok(self.read_map()?.get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut map = self.read_map()?;
+        map.insert(key.to_string(), value.to_string());
+        self.write_map(&map)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut map = self.read_map()?;
+        map.remove(key);
+        self.write_map(&map)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "environment variables (TALPA_*) and {}",
+            self.path.display()
+        )
+    }
 }
 
 fn read_input(prompt: &str) -> Result<String> {
@@ -106,22 +373,26 @@ fn read_secret(prompt: &str) -> Result<String> {
 
 struct Credentials {
     account_id: String,
-    zone_id: String,
+    // Explicit zone override; when absent the zone is auto-discovered per hostname.
+    zone_id: Option<String>,
     tunnel_id: String,
     api_token: String,
 }
 
 impl Credentials {
-    fn from_keychain() -> Result<Self> {
+    fn load(store: &dyn CredentialStore) -> Result<Self> {
+        let required = |key: &str| -> Result<String> {
+            store
+                .get(key)?
+                .filter(|v| !v.is_empty())
+                .context("Run `talpa setup` to configure credentials")
+        };
+
         Ok(Self {
-            account_id: keychain_get("account_id")
-                .context("Run `tunnel setup` to configure credentials")?,
-            zone_id: keychain_get("zone_id")
-                .context("Run `tunnel setup` to configure credentials")?,
-            tunnel_id: keychain_get("tunnel_id")
-                .context("Run `tunnel setup` to configure credentials")?,
-            api_token: keychain_get("api_token")
-                .context("Run `tunnel setup` to configure credentials")?,
+            account_id: required("account_id")?,
+            zone_id: store.get("zone_id")?.filter(|v| !v.is_empty()),
+            tunnel_id: required("tunnel_id")?,
+            api_token: required("api_token")?,
         })
     }
 }
@@ -145,6 +416,32 @@ struct DnsRecord {
     id: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct DnsRecordListing {
+    id: String,
+    name: String,
+    content: String,
+    #[serde(rename = "type")]
+    record_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Zone {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TunnelSummary {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteSummary {
+    id: String,
+    network: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct IngressRule {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -154,6 +451,49 @@ struct IngressRule {
     origin_request: Option<serde_json::Value>,
 }
 
+// Assembles an originRequest object from dig's per-route flags, or None if none were passed.
+fn build_origin_request(
+    no_tls_verify: bool,
+    http_host_header: &Option<String>,
+    origin_server_name: &Option<String>,
+    connect_timeout: Option<u64>,
+) -> Option<serde_json::Value> {
+    let mut opts = serde_json::Map::new();
+
+    if no_tls_verify {
+        opts.insert("noTLSVerify".to_string(), serde_json::json!(true));
+    }
+    if let Some(header) = http_host_header {
+        opts.insert("httpHostHeader".to_string(), serde_json::json!(header));
+    }
+    if let Some(name) = origin_server_name {
+        opts.insert("originServerName".to_string(), serde_json::json!(name));
+    }
+    if let Some(secs) = connect_timeout {
+        opts.insert(
+            "connectTimeout".to_string(),
+            serde_json::json!(format!("{secs}s")),
+        );
+    }
+
+    if opts.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(opts))
+    }
+}
+
+// Renders a rule's originRequest as "key=value, key=value" for `list`, or "" if unset.
+fn format_origin_request(origin_request: &Option<serde_json::Value>) -> String {
+    let Some(serde_json::Value::Object(opts)) = origin_request else {
+        return String::new();
+    };
+    opts.iter()
+        .map(|(k, v)| format!("{k}={}", v.to_string().trim_matches('"')))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Debug, Deserialize)]
 struct TunnelConfigResult {
     config: TunnelConfigInner,
@@ -173,12 +513,23 @@ struct TunnelConfigUpdate {
 
 // ─── Cloudflare API Client ───────────────────────────────────
 
+// Progressively shorter suffixes of `hostname`, dropping the left-most label each time.
+fn zone_candidates(hostname: &str) -> Result<Vec<String>> {
+    let labels: Vec<&str> = hostname.split('.').collect();
+    if labels.len() < 2 {
+        bail!("'{hostname}' is not a valid hostname to resolve a zone for");
+    }
+    Ok((0..labels.len() - 1).map(|start| labels[start..].join(".")).collect())
+}
+
 struct CfClient {
     client: reqwest::blocking::Client,
     account_id: String,
-    zone_id: String,
+    zone_id: Option<String>,
     tunnel_id: String,
     api_token: String,
+    // Zones discovered via `resolve_zone`, keyed by hostname.
+    zone_cache: RefCell<HashMap<String, String>>,
 }
 
 impl CfClient {
@@ -189,14 +540,12 @@ impl CfClient {
             zone_id: creds.zone_id.clone(),
             tunnel_id: creds.tunnel_id.clone(),
             api_token: creds.api_token.clone(),
+            zone_cache: RefCell::new(HashMap::new()),
         }
     }
 
-    fn dns_url(&self) -> String {
-        format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-            self.zone_id
-        )
+    fn dns_url(&self, zone_id: &str) -> String {
+        format!("https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records")
     }
 
     fn tunnel_config_url(&self) -> String {
@@ -206,14 +555,41 @@ impl CfClient {
         )
     }
 
+    fn resolve_zone(&self, hostname: &str) -> Result<String> {
+        if let Some(id) = &self.zone_id {
+            return Ok(id.clone());
+        }
+        if let Some(id) = self.zone_cache.borrow().get(hostname) {
+            return Ok(id.clone());
+        }
+
+        let candidates = zone_candidates(hostname)?;
+
+        for candidate in candidates {
+            let url = format!("https://api.cloudflare.com/client/v4/zones?name={candidate}");
+            let resp: CfResponse<Vec<Zone>> = self
+                .client
+                .get(&url)
+                .bearer_auth(&self.api_token)
+                .send()?
+                .json()?;
+
+            if let Some(zone) = resp.result.unwrap_or_default().into_iter().next() {
+                self.zone_cache
+                    .borrow_mut()
+                    .insert(hostname.to_string(), zone.id.clone());
+                return Ok(zone.id);
+            }
+        }
+
+        bail!("No Cloudflare zone found for '{hostname}' or any of its parent domains");
+    }
+
     fn verify_connection(&self) -> Result<()> {
-        let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}",
-            self.zone_id
-        );
+        let url = "https://api.cloudflare.com/client/v4/user/tokens/verify";
         let resp: CfResponse<serde_json::Value> = self
             .client
-            .get(&url)
+            .get(url)
             .bearer_auth(&self.api_token)
             .send()?
             .json()?;
@@ -258,28 +634,41 @@ impl CfClient {
     }
 
     fn create_cname(&self, hostname: &str) -> Result<()> {
-        let resp: CfResponse<serde_json::Value> = self
-            .client
-            .post(&self.dns_url())
-            .bearer_auth(&self.api_token)
-            .json(&serde_json::json!({
-                "type": "CNAME",
-                "name": hostname,
-                "content": format!("{}.cfargotunnel.com", self.tunnel_id),
-                "proxied": true
-            }))
-            .send()?
-            .json()?;
+        let zone_id = self.resolve_zone(hostname)?;
+        let body = serde_json::json!({
+            "type": "CNAME",
+            "name": hostname,
+            "content": format!("{}.cfargotunnel.com", self.tunnel_id),
+            "proxied": true
+        });
+
+        let resp: CfResponse<serde_json::Value> = match self.find_record_id(hostname)? {
+            Some(id) => self
+                .client
+                .put(format!("{}/{}", self.dns_url(&zone_id), id))
+                .bearer_auth(&self.api_token)
+                .json(&body)
+                .send()?
+                .json()?,
+            None => self
+                .client
+                .post(self.dns_url(&zone_id))
+                .bearer_auth(&self.api_token)
+                .json(&body)
+                .send()?
+                .json()?,
+        };
 
         if !resp.success {
             let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
-            bail!("CNAME creation failed: {}", msgs.join(", "));
+            bail!("CNAME upsert failed: {}", msgs.join(", "));
         }
         Ok(())
     }
 
     fn find_record_id(&self, hostname: &str) -> Result<Option<String>> {
-        let url = format!("{}?type=CNAME&name={}", self.dns_url(), hostname);
+        let zone_id = self.resolve_zone(hostname)?;
+        let url = format!("{}?type=CNAME&name={}", self.dns_url(&zone_id), hostname);
         let resp: CfResponse<Vec<DnsRecord>> = self
             .client
             .get(&url)
@@ -294,24 +683,332 @@ impl CfClient {
             .map(|r| r.id.clone()))
     }
 
-    fn delete_record(&self, record_id: &str) -> Result<()> {
-        let url = format!("{}/{}", self.dns_url(), record_id);
+    fn delete_record(&self, hostname: &str, record_id: &str) -> Result<()> {
+        let zone_id = self.resolve_zone(hostname)?;
+        self.delete_record_in_zone(&zone_id, record_id)
+    }
+
+    fn delete_record_in_zone(&self, zone_id: &str, record_id: &str) -> Result<()> {
+        let url = format!("{}/{}", self.dns_url(zone_id), record_id);
         self.client
             .delete(&url)
             .bearer_auth(&self.api_token)
             .send()?;
         Ok(())
     }
+
+    fn list_dns_records(&self, zone_id: &str) -> Result<Vec<DnsRecordListing>> {
+        let url = format!("{}?per_page=1000", self.dns_url(zone_id));
+        let resp: CfResponse<Vec<DnsRecordListing>> = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.api_token)
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to list DNS records: {}", msgs.join(", "));
+        }
+        Ok(resp.result.unwrap_or_default())
+    }
+
+    fn tunnels_url(&self) -> String {
+        format!(
+            "https://api.cloudflare.com/client/v4/accounts/{}/cfd_tunnel",
+            self.account_id
+        )
+    }
+
+    fn routes_url(&self) -> String {
+        format!(
+            "https://api.cloudflare.com/client/v4/accounts/{}/teamnet/routes",
+            self.account_id
+        )
+    }
+
+    fn list_tunnels(&self) -> Result<Vec<TunnelSummary>> {
+        let resp: CfResponse<Vec<TunnelSummary>> = self
+            .client
+            .get(self.tunnels_url())
+            .bearer_auth(&self.api_token)
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to list tunnels: {}", msgs.join(", "));
+        }
+        Ok(resp.result.unwrap_or_default())
+    }
+
+    fn create_tunnel(&self, name: &str) -> Result<String> {
+        create_tunnel_raw(&self.client, &self.account_id, &self.api_token, name)
+    }
+
+    fn delete_tunnel(&self, tunnel_id: &str) -> Result<()> {
+        let url = format!("{}/{tunnel_id}", self.tunnels_url());
+        let resp: CfResponse<serde_json::Value> = self
+            .client
+            .delete(&url)
+            .bearer_auth(&self.api_token)
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to delete tunnel: {}", msgs.join(", "));
+        }
+        Ok(())
+    }
+
+    fn list_routes(&self) -> Result<Vec<RouteSummary>> {
+        let resp: CfResponse<Vec<RouteSummary>> = self
+            .client
+            .get(self.routes_url())
+            .bearer_auth(&self.api_token)
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to list IP routes: {}", msgs.join(", "));
+        }
+        Ok(resp.result.unwrap_or_default())
+    }
+
+    fn add_route(&self, cidr: &str) -> Result<()> {
+        let resp: CfResponse<serde_json::Value> = self
+            .client
+            .post(self.routes_url())
+            .bearer_auth(&self.api_token)
+            .json(&serde_json::json!({
+                "network": cidr,
+                "tunnel_id": self.tunnel_id,
+            }))
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to add IP route: {}", msgs.join(", "));
+        }
+        Ok(())
+    }
+
+    fn remove_route(&self, cidr: &str) -> Result<()> {
+        let route_id = self
+            .list_routes()?
+            .into_iter()
+            .find(|r| r.network == cidr)
+            .map(|r| r.id)
+            .with_context(|| format!("No IP route found for '{cidr}'"))?;
+
+        let url = format!("{}/{route_id}", self.routes_url());
+        let resp: CfResponse<serde_json::Value> = self
+            .client
+            .delete(&url)
+            .bearer_auth(&self.api_token)
+            .send()?
+            .json()?;
+
+        if !resp.success {
+            let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+            bail!("Failed to remove IP route: {}", msgs.join(", "));
+        }
+        Ok(())
+    }
+}
+
+// Standalone so `setup` can provision a tunnel before a full Credentials/CfClient exists.
+fn create_tunnel_raw(
+    client: &reqwest::blocking::Client,
+    account_id: &str,
+    api_token: &str,
+    name: &str,
+) -> Result<String> {
+    let url = format!("https://api.cloudflare.com/client/v4/accounts/{account_id}/cfd_tunnel");
+    let resp: CfResponse<TunnelSummary> = client
+        .post(&url)
+        .bearer_auth(api_token)
+        .json(&serde_json::json!({
+            "name": name,
+            "config_src": "cloudflare",
+        }))
+        .send()?
+        .json()?;
+
+    if !resp.success {
+        let msgs: Vec<_> = resp.errors.iter().map(|e| e.message.as_str()).collect();
+        bail!("Tunnel creation failed: {}", msgs.join(", "));
+    }
+    Ok(resp.result.context("No tunnel returned")?.id)
+}
+
+// ─── DNS Propagation ─────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(default)]
+    #[serde(rename = "Answer")]
+    answer: Vec<DohAnswer>,
+}
+
+// Polls Cloudflare's own DoH resolver until `hostname` resolves to the tunnel's target.
+fn wait_for_propagation(hostname: &str, tunnel_id: &str, timeout: Duration) -> Result<bool> {
+    let client = reqwest::blocking::Client::new();
+    let target = format!("{tunnel_id}.cfargotunnel.com");
+    let start = Instant::now();
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        print!("\r  {} Waiting for DNS propagation...", "→".dimmed());
+        use std::io::Write;
+        std::io::stdout().flush().ok();
+
+        let resolved = client
+            .get("https://cloudflare-dns.com/dns-query")
+            .header("Accept", "application/dns-json")
+            .query(&[("name", hostname), ("type", "CNAME")])
+            .send()
+            .ok()
+            .and_then(|r| r.json::<DohResponse>().ok())
+            .map(|r| {
+                r.answer
+                    .iter()
+                    .any(|a| a.data.trim_end_matches('.') == target)
+            })
+            .unwrap_or(false);
+
+        if resolved {
+            println!("\r  {} DNS propagated                     ", "→".green());
+            return Ok(true);
+        }
+
+        if start.elapsed() >= timeout {
+            println!(
+                "\r  {} DNS not yet visible after {}s (it may still be propagating)",
+                "⚠".yellow(),
+                timeout.as_secs()
+            );
+            return Ok(false);
+        }
+
+        std::thread::sleep(delay.min(timeout.saturating_sub(start.elapsed())));
+        delay = (delay * 2).min(Duration::from_secs(8));
+    }
+}
+
+// ─── Declarative Routes ──────────────────────────────────────
+
+// Desired-state routes file: `[routes]` maps hostname -> service.
+#[derive(Debug, Deserialize)]
+struct RoutesFile {
+    #[serde(default)]
+    catch_all: Option<String>,
+    #[serde(default)]
+    routes: BTreeMap<String, String>,
+}
+
+fn load_routes_file(path: &Path) -> Result<RoutesFile> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read routes file: {}", path.display()))?;
+    toml::from_str(&raw).with_context(|| format!("Failed to parse routes file: {}", path.display()))
+}
+
+#[derive(Debug, Clone)]
+enum RouteChange {
+    Add { hostname: String, service: String },
+    Update { hostname: String, old_service: String, new_service: String },
+    Remove { hostname: String, service: String },
+}
+
+// The trailing catch-all rule is handled separately and never appears in this diff.
+fn diff_routes(desired: &RoutesFile, live: &TunnelConfigInner) -> Vec<RouteChange> {
+    let live_routes: BTreeMap<&str, &str> = live
+        .ingress
+        .iter()
+        .filter_map(|r| r.hostname.as_deref().map(|h| (h, r.service.as_str())))
+        .collect();
+
+    let mut changes = Vec::new();
+
+    for (hostname, service) in &desired.routes {
+        match live_routes.get(hostname.as_str()) {
+            Some(live_service) if *live_service != service => changes.push(RouteChange::Update {
+                hostname: hostname.clone(),
+                old_service: live_service.to_string(),
+                new_service: service.clone(),
+            }),
+            Some(_) => {}
+            None => changes.push(RouteChange::Add {
+                hostname: hostname.clone(),
+                service: service.clone(),
+            }),
+        }
+    }
+
+    for (hostname, service) in &live_routes {
+        if !desired.routes.contains_key(*hostname) {
+            changes.push(RouteChange::Remove {
+                hostname: hostname.to_string(),
+                service: service.to_string(),
+            });
+        }
+    }
+
+    changes
+}
+
+fn print_diff(changes: &[RouteChange], prune: bool) {
+    if changes.is_empty() {
+        println!("  {} (no changes)", "=".dimmed());
+        return;
+    }
+    for change in changes {
+        match change {
+            RouteChange::Add { hostname, service } => {
+                println!("  {} {} → {}", "+".green().bold(), hostname, service.green());
+            }
+            RouteChange::Update { hostname, old_service, new_service } => {
+                println!(
+                    "  {} {} → {} {} {}",
+                    "~".yellow().bold(),
+                    hostname,
+                    old_service.dimmed(),
+                    "=>".dimmed(),
+                    new_service.yellow()
+                );
+            }
+            RouteChange::Remove { hostname, service } => {
+                if prune {
+                    println!("  {} {} → {}", "-".red().bold(), hostname, service.red());
+                } else {
+                    println!(
+                        "  {} {} → {} {}",
+                        "-".dimmed(),
+                        hostname,
+                        service.dimmed(),
+                        "(unmanaged, pass --prune to remove)".dimmed()
+                    );
+                }
+            }
+        }
+    }
 }
 
 // ─── Commands ────────────────────────────────────────────────
 
-fn cmd_setup() -> Result<()> {
+fn cmd_setup(store: &dyn CredentialStore) -> Result<()> {
     println!();
     println!("{}", " 🔧 Tunnel CLI Setup ".bold().on_blue().white());
     println!();
-    println!("Credentials will be stored in your macOS Keychain");
-    println!("under the service: {}", KEYCHAIN_SERVICE.dimmed());
+    println!("Credentials will be stored in: {}", store.describe().dimmed());
     println!();
 
     let account_id = read_input(&format!("  {} Account ID: ", "→".dimmed()))?;
@@ -319,30 +1016,51 @@ fn cmd_setup() -> Result<()> {
         bail!("Account ID cannot be empty");
     }
 
-    let zone_id = read_input(&format!("  {} Zone ID: ", "→".dimmed()))?;
-    if zone_id.is_empty() {
-        bail!("Zone ID cannot be empty");
-    }
-
-    let tunnel_id = read_input(&format!("  {} Tunnel ID: ", "→".dimmed()))?;
-    if tunnel_id.is_empty() {
-        bail!("Tunnel ID cannot be empty");
-    }
+    let zone_id = read_input(&format!(
+        "  {} Zone ID (optional, leave blank to auto-detect per hostname): ",
+        "→".dimmed()
+    ))?;
 
     let api_token = read_secret(&format!("  {} API Token (hidden): ", "→".dimmed()))?;
     if api_token.is_empty() {
         bail!("API Token cannot be empty");
     }
 
-    print!("  {} Saving to Keychain...", "→".dimmed());
-    keychain_set("account_id", &account_id)?;
-    keychain_set("zone_id", &zone_id)?;
-    keychain_set("tunnel_id", &tunnel_id)?;
-    keychain_set("api_token", &api_token)?;
+    let tunnel_id = read_input(&format!(
+        "  {} Tunnel ID (leave blank to create a new tunnel): ",
+        "→".dimmed()
+    ))?;
+    let tunnel_id = if tunnel_id.is_empty() {
+        let tunnel_name = read_input(&format!("  {} New tunnel name: ", "→".dimmed()))?;
+        if tunnel_name.is_empty() {
+            bail!("Tunnel ID or name is required");
+        }
+        print!("  {} Creating tunnel '{}'...", "→".dimmed(), tunnel_name);
+        let id = create_tunnel_raw(
+            &reqwest::blocking::Client::new(),
+            &account_id,
+            &api_token,
+            &tunnel_name,
+        )?;
+        println!(" {}", "ok".green());
+        id
+    } else {
+        tunnel_id
+    };
+
+    print!("  {} Saving credentials...", "→".dimmed());
+    store.set("account_id", &account_id)?;
+    if zone_id.is_empty() {
+        store.delete("zone_id")?;
+    } else {
+        store.set("zone_id", &zone_id)?;
+    }
+    store.set("tunnel_id", &tunnel_id)?;
+    store.set("api_token", &api_token)?;
     println!(" {}", "ok".green());
 
     print!("  {} Verifying...", "→".dimmed());
-    let creds = Credentials::from_keychain()?;
+    let creds = Credentials::load(store)?;
     let cf = CfClient::new(&creds);
     match cf.verify_connection() {
         Ok(()) => println!(" {}", "ok".green()),
@@ -369,46 +1087,77 @@ fn cmd_setup() -> Result<()> {
     Ok(())
 }
 
-fn cmd_add(hostname: &str, service: &str) -> Result<()> {
-    let creds = Credentials::from_keychain()?;
+#[allow(clippy::too_many_arguments)]
+fn cmd_add(
+    store: &dyn CredentialStore,
+    hostname: &str,
+    service: &str,
+    wait: bool,
+    timeout: Option<u64>,
+    no_tls_verify: bool,
+    http_host_header: Option<String>,
+    origin_server_name: Option<String>,
+    connect_timeout: Option<u64>,
+) -> Result<()> {
+    let creds = Credentials::load(store)?;
     let cf = CfClient::new(&creds);
 
+    let origin_request = build_origin_request(
+        no_tls_verify,
+        &http_host_header,
+        &origin_server_name,
+        connect_timeout,
+    );
+
     print!("  {} Fetching tunnel config...", "→".dimmed());
     let mut config = cf.get_tunnel_config()?;
     println!(" {}", "ok".green());
 
-    if config
+    if let Some(existing) = config
         .ingress
-        .iter()
-        .any(|r| r.hostname.as_deref() == Some(hostname))
+        .iter_mut()
+        .find(|r| r.hostname.as_deref() == Some(hostname))
     {
-        bail!("{hostname} already exists in tunnel config");
+        existing.service = service.to_string();
+        if origin_request.is_some() {
+            existing.origin_request = origin_request.clone();
+        }
+    } else {
+        let catch_all = config.ingress.pop().context("No catch-all rule found")?;
+        config.ingress.push(IngressRule {
+            hostname: Some(hostname.to_string()),
+            service: service.to_string(),
+            origin_request: origin_request.clone(),
+        });
+        config.ingress.push(catch_all);
     }
 
-    let catch_all = config.ingress.pop().context("No catch-all rule found")?;
-    config.ingress.push(IngressRule {
-        hostname: Some(hostname.to_string()),
-        service: service.to_string(),
-        origin_request: None,
-    });
-    config.ingress.push(catch_all);
-
     print!("  {} Updating tunnel config...", "→".dimmed());
     cf.put_tunnel_config(config)?;
     println!(" {}", "ok".green());
 
     print!("  {} Creating CNAME...", "→".dimmed());
-    match cf.create_cname(hostname) {
-        Ok(()) => println!(" {}", "ok".green()),
-        Err(e) => println!(" {} {e}", "⚠".yellow()),
+    let cname_created = match cf.create_cname(hostname) {
+        Ok(()) => {
+            println!(" {}", "ok".green());
+            true
+        }
+        Err(e) => {
+            println!(" {} {e}", "⚠".yellow());
+            false
+        }
+    };
+
+    if cname_created && (wait || timeout.is_some()) {
+        wait_for_propagation(hostname, &creds.tunnel_id, Duration::from_secs(timeout.unwrap_or(30)))?;
     }
 
     println!("\n{} {} → {}", "✅".green(), hostname.bold(), service);
     Ok(())
 }
 
-fn cmd_remove(hostname: &str) -> Result<()> {
-    let creds = Credentials::from_keychain()?;
+fn cmd_remove(store: &dyn CredentialStore, hostname: &str) -> Result<()> {
+    let creds = Credentials::load(store)?;
     let cf = CfClient::new(&creds);
 
     print!("  {} Fetching tunnel config...", "→".dimmed());
@@ -431,7 +1180,7 @@ fn cmd_remove(hostname: &str) -> Result<()> {
     print!("  {} Removing CNAME...", "→".dimmed());
     match cf.find_record_id(hostname)? {
         Some(id) => {
-            cf.delete_record(&id)?;
+            cf.delete_record(hostname, &id)?;
             println!(" {}", "ok".green());
         }
         None => println!(" {} not found (skipped)", "⚠".yellow()),
@@ -441,8 +1190,8 @@ fn cmd_remove(hostname: &str) -> Result<()> {
     Ok(())
 }
 
-fn cmd_list() -> Result<()> {
-    let creds = Credentials::from_keychain()?;
+fn cmd_list(store: &dyn CredentialStore) -> Result<()> {
+    let creds = Credentials::load(store)?;
     let cf = CfClient::new(&creds);
 
     let config = cf.get_tunnel_config()?;
@@ -454,19 +1203,25 @@ fn cmd_list() -> Result<()> {
 
     let mut count = 0;
     for rule in &config.ingress {
+        let origin = format_origin_request(&rule.origin_request);
         match &rule.hostname {
             Some(host) => {
-                println!("  {:<40} → {}", host.cyan(), rule.service.green());
+                print!("  {:<40} → {}", host.cyan(), rule.service.green());
                 count += 1;
             }
             None => {
-                println!(
+                print!(
                     "  {:<40} → {}",
                     "* (catch-all)".dimmed(),
                     rule.service.dimmed()
                 );
             }
         }
+        if origin.is_empty() {
+            println!();
+        } else {
+            println!("  {}", format!("[{origin}]").dimmed());
+        }
     }
 
     println!();
@@ -475,15 +1230,364 @@ fn cmd_list() -> Result<()> {
     Ok(())
 }
 
+fn cmd_diff(store: &dyn CredentialStore, file: &Path) -> Result<()> {
+    let creds = Credentials::load(store)?;
+    let cf = CfClient::new(&creds);
+
+    let desired = load_routes_file(file)?;
+    let live = cf.get_tunnel_config()?;
+    let changes = diff_routes(&desired, &live);
+
+    println!();
+    println!("{}", " 📝 Route Plan ".bold().on_blue().white());
+    println!();
+    print_diff(&changes, false);
+    println!();
+    Ok(())
+}
+
+fn cmd_apply(store: &dyn CredentialStore, file: &Path, prune: bool) -> Result<()> {
+    let creds = Credentials::load(store)?;
+    let cf = CfClient::new(&creds);
+
+    let desired = load_routes_file(file)?;
+    let mut config = cf.get_tunnel_config()?;
+    let changes = diff_routes(&desired, &config);
+
+    println!();
+    println!("{}", " 📝 Applying Routes ".bold().on_blue().white());
+    println!();
+    print_diff(&changes, prune);
+    println!();
+
+    let catch_all_idx = config
+        .ingress
+        .iter()
+        .position(|r| r.hostname.is_none())
+        .context("No catch-all rule found")?;
+    if let Some(service) = &desired.catch_all {
+        config.ingress[catch_all_idx].service = service.clone();
+    }
+    let catch_all = config.ingress.remove(catch_all_idx);
+
+    for change in &changes {
+        match change {
+            RouteChange::Add { hostname, service } => {
+                config.ingress.push(IngressRule {
+                    hostname: Some(hostname.clone()),
+                    service: service.clone(),
+                    origin_request: None,
+                });
+            }
+            RouteChange::Update { hostname, new_service, .. } => {
+                if let Some(rule) = config
+                    .ingress
+                    .iter_mut()
+                    .find(|r| r.hostname.as_deref() == Some(hostname.as_str()))
+                {
+                    rule.service = new_service.clone();
+                }
+            }
+            RouteChange::Remove { hostname, .. } => {
+                if prune {
+                    config
+                        .ingress
+                        .retain(|r| r.hostname.as_deref() != Some(hostname.as_str()));
+                }
+            }
+        }
+    }
+    config.ingress.push(catch_all);
+
+    print!("  {} Updating tunnel config...", "→".dimmed());
+    cf.put_tunnel_config(config)?;
+    println!(" {}", "ok".green());
+
+    for change in &changes {
+        match change {
+            RouteChange::Add { hostname, .. } | RouteChange::Update { hostname, .. } => {
+                print!("  {} Upserting CNAME for {hostname}...", "→".dimmed());
+                match cf.create_cname(hostname) {
+                    Ok(()) => println!(" {}", "ok".green()),
+                    Err(e) => println!(" {} {e}", "⚠".yellow()),
+                }
+            }
+            RouteChange::Remove { hostname, .. } if prune => {
+                print!("  {} Removing CNAME for {hostname}...", "→".dimmed());
+                match cf.find_record_id(hostname)? {
+                    Some(id) => {
+                        cf.delete_record(hostname, &id)?;
+                        println!(" {}", "ok".green());
+                    }
+                    None => println!(" {} not found (skipped)", "⚠".yellow()),
+                }
+            }
+            RouteChange::Remove { .. } => {}
+        }
+    }
+
+    let applied = changes
+        .iter()
+        .filter(|c| prune || !matches!(c, RouteChange::Remove { .. }))
+        .count();
+    println!("\n{} Applied {} change(s)", "✅".green(), applied);
+    Ok(())
+}
+
+fn cmd_prune(store: &dyn CredentialStore, zone: &str, dry_run: bool, yes: bool) -> Result<()> {
+    let creds = Credentials::load(store)?;
+    let cf = CfClient::new(&creds);
+
+    let zone_id = cf.resolve_zone(zone)?;
+    let target = format!("{}.cfargotunnel.com", creds.tunnel_id);
+
+    print!("  {} Listing DNS records...", "→".dimmed());
+    let records = cf.list_dns_records(&zone_id)?;
+    println!(" {}", "ok".green());
+
+    let config = cf.get_tunnel_config()?;
+    let live_hostnames: std::collections::HashSet<&str> = config
+        .ingress
+        .iter()
+        .filter_map(|r| r.hostname.as_deref())
+        .collect();
+
+    let orphans: Vec<&DnsRecordListing> = records
+        .iter()
+        .filter(|r| r.record_type == "CNAME" && r.content.trim_end_matches('.') == target)
+        .filter(|r| !live_hostnames.contains(r.name.as_str()))
+        .collect();
+
+    println!();
+    println!("{}", " 🧹 Orphaned Tunnel CNAMEs ".bold().on_blue().white());
+    println!();
+    if orphans.is_empty() {
+        println!("  {} nothing to prune", "=".dimmed());
+        println!();
+        return Ok(());
+    }
+    for record in &orphans {
+        println!("  {} {} → {}", "-".red().bold(), record.name, record.content.dimmed());
+    }
+    println!();
+    println!("  {} orphaned record(s)", orphans.len().to_string().bold());
+    println!();
+
+    if dry_run {
+        println!("  {} dry run, no records deleted", "→".dimmed());
+        return Ok(());
+    }
+
+    if !yes {
+        let answer = read_input(&format!(
+            "  {} Delete {} record(s)? [y/N] ",
+            "→".dimmed(),
+            orphans.len()
+        ))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            println!("  {} aborted", "→".dimmed());
+            return Ok(());
+        }
+    }
+
+    for record in &orphans {
+        print!("  {} Deleting {}...", "→".dimmed(), record.name);
+        cf.delete_record_in_zone(&zone_id, &record.id)?;
+        println!(" {}", "ok".green());
+    }
+
+    println!("\n{} Pruned {} record(s)", "✅".green(), orphans.len());
+    Ok(())
+}
+
+fn cmd_tunnel(store: &dyn CredentialStore, action: TunnelAction) -> Result<()> {
+    let creds = Credentials::load(store)?;
+    let cf = CfClient::new(&creds);
+
+    match action {
+        TunnelAction::Create { name } => {
+            print!("  {} Creating tunnel '{name}'...", "→".dimmed());
+            let id = cf.create_tunnel(&name)?;
+            println!(" {}", "ok".green());
+            println!("\n{} {} → {}", "✅".green(), name.bold(), id);
+        }
+        TunnelAction::Delete { id } => {
+            print!("  {} Deleting tunnel {id}...", "→".dimmed());
+            cf.delete_tunnel(&id)?;
+            println!(" {}", "ok".green());
+        }
+        TunnelAction::Ls => {
+            let tunnels = cf.list_tunnels()?;
+            println!();
+            println!("{}", " 🚇 Tunnels ".bold().on_blue().white());
+            println!();
+            for tunnel in &tunnels {
+                println!("  {:<36} {}", tunnel.id.dimmed(), tunnel.name.cyan());
+            }
+            println!();
+            println!("  Total: {} tunnel(s)", tunnels.len().to_string().bold());
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn cmd_route_ip(store: &dyn CredentialStore, action: RouteIpAction) -> Result<()> {
+    let creds = Credentials::load(store)?;
+    let cf = CfClient::new(&creds);
+
+    match action {
+        RouteIpAction::Add { cidr } => {
+            print!("  {} Routing {cidr}...", "→".dimmed());
+            cf.add_route(&cidr)?;
+            println!(" {}", "ok".green());
+        }
+        RouteIpAction::Rm { cidr } => {
+            print!("  {} Removing route {cidr}...", "→".dimmed());
+            cf.remove_route(&cidr)?;
+            println!(" {}", "ok".green());
+        }
+        RouteIpAction::Ls => {
+            let routes = cf.list_routes()?;
+            println!();
+            println!("{}", " 🛰️  IP Routes ".bold().on_blue().white());
+            println!();
+            for route in &routes {
+                println!("  {}", route.network.cyan());
+            }
+            println!();
+            println!("  Total: {} route(s)", routes.len().to_string().bold());
+            println!();
+        }
+    }
+    Ok(())
+}
+
 // ─── Main ────────────────────────────────────────────────────
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let store = resolve_backend(cli.credential_backend);
 
     match cli.command {
-        Commands::Setup => cmd_setup(),
-        Commands::Dig { hostname, service } => cmd_add(&hostname, &service),
-        Commands::Plug { hostname } => cmd_remove(&hostname),
-        Commands::List => cmd_list(),
+        Commands::Setup => cmd_setup(store.as_ref()),
+        Commands::Dig {
+            hostname,
+            service,
+            wait,
+            timeout,
+            no_tls_verify,
+            http_host_header,
+            origin_server_name,
+            connect_timeout,
+        } => cmd_add(
+            store.as_ref(),
+            &hostname,
+            &service,
+            wait,
+            timeout,
+            no_tls_verify,
+            http_host_header,
+            origin_server_name,
+            connect_timeout,
+        ),
+        Commands::Plug { hostname } => cmd_remove(store.as_ref(), &hostname),
+        Commands::List => cmd_list(store.as_ref()),
+        Commands::Diff { file } => cmd_diff(store.as_ref(), &file),
+        Commands::Apply { file, prune } => cmd_apply(store.as_ref(), &file, prune),
+        Commands::Prune { zone, dry_run, yes } => cmd_prune(store.as_ref(), &zone, dry_run, yes),
+        Commands::Tunnel { action } => cmd_tunnel(store.as_ref(), action),
+        Commands::RouteIp { action } => cmd_route_ip(store.as_ref(), action),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zone_candidates_walks_suffixes() {
+        assert_eq!(
+            zone_candidates("app.foo.example.com").unwrap(),
+            vec!["app.foo.example.com", "foo.example.com", "example.com"]
+        );
+        assert_eq!(zone_candidates("example.com").unwrap(), vec!["example.com"]);
+    }
+
+    #[test]
+    fn zone_candidates_rejects_bare_label() {
+        assert!(zone_candidates("localhost").is_err());
+    }
+
+    fn rule(hostname: &str, service: &str) -> IngressRule {
+        IngressRule {
+            hostname: Some(hostname.to_string()),
+            service: service.to_string(),
+            origin_request: None,
+        }
+    }
+
+    fn live_config(rules: Vec<IngressRule>) -> TunnelConfigInner {
+        TunnelConfigInner { ingress: rules, extra: serde_json::Map::new() }
+    }
+
+    fn desired(routes: &[(&str, &str)]) -> RoutesFile {
+        RoutesFile {
+            catch_all: None,
+            routes: routes.iter().map(|(h, s)| (h.to_string(), s.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn diff_routes_detects_add() {
+        let live = live_config(vec![]);
+        let changes = diff_routes(&desired(&[("app.example.com", "http://localhost:8080")]), &live);
+        assert!(matches!(
+            changes.as_slice(),
+            [RouteChange::Add { hostname, service }]
+                if hostname == "app.example.com" && service == "http://localhost:8080"
+        ));
+    }
+
+    #[test]
+    fn diff_routes_detects_update() {
+        let live = live_config(vec![rule("app.example.com", "http://localhost:8080")]);
+        let changes = diff_routes(&desired(&[("app.example.com", "http://localhost:9090")]), &live);
+        assert!(matches!(
+            changes.as_slice(),
+            [RouteChange::Update { hostname, old_service, new_service }]
+                if hostname == "app.example.com"
+                    && old_service == "http://localhost:8080"
+                    && new_service == "http://localhost:9090"
+        ));
+    }
+
+    #[test]
+    fn diff_routes_detects_remove() {
+        let live = live_config(vec![rule("app.example.com", "http://localhost:8080")]);
+        let changes = diff_routes(&desired(&[]), &live);
+        assert!(matches!(
+            changes.as_slice(),
+            [RouteChange::Remove { hostname, service }]
+                if hostname == "app.example.com" && service == "http://localhost:8080"
+        ));
+    }
+
+    #[test]
+    fn diff_routes_no_op_when_unchanged() {
+        let live = live_config(vec![rule("app.example.com", "http://localhost:8080")]);
+        let changes = diff_routes(&desired(&[("app.example.com", "http://localhost:8080")]), &live);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn diff_routes_ignores_catch_all() {
+        let live = live_config(vec![IngressRule {
+            hostname: None,
+            service: "http_status:404".to_string(),
+            origin_request: None,
+        }]);
+        let changes = diff_routes(&desired(&[]), &live);
+        assert!(changes.is_empty());
     }
 }